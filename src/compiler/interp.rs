@@ -0,0 +1,210 @@
+//! Portable interpreter backend: executes an [`ir::Program`](crate::ir::Program)
+//! directly, with no codegen at all. Used as a fallback when neither JIT
+//! backend is wanted (or available) and for deterministic testing, since
+//! it has no codegen variance to account for and can be capped to
+//! guarantee termination.
+
+use super::ExitStatus;
+use crate::ir::{Instruction, Program};
+use std::io::{Read, Write};
+
+/// Executes `program` against `input`/`output`.
+///
+/// `budget` caps the number of IR instructions executed; a non-terminating
+/// program halts with [`ExitStatus::BudgetExhausted`] instead of spinning
+/// forever, rather than requiring the caller to kill it externally.
+pub fn run(
+    program: &Program,
+    budget: u64,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> ExitStatus {
+    let mut tape = vec![0u8; 1];
+    let mut head = 0usize;
+    let mut ip = 0usize;
+    let mut steps = 0u64;
+
+    while ip < program.code.len() {
+        if steps >= budget {
+            return ExitStatus::BudgetExhausted;
+        }
+        steps += 1;
+
+        match program.code[ip] {
+            Instruction::Add(delta) => {
+                tape[head] = tape[head].wrapping_add(delta as u8);
+                ip += 1;
+            },
+            Instruction::Move(delta) => {
+                head = move_head(&mut tape, head, delta);
+                ip += 1;
+            },
+            Instruction::SetZero => {
+                tape[head] = 0;
+                ip += 1;
+            },
+            Instruction::Inc => {
+                tape[head] = tape[head].wrapping_add(1);
+                ip += 1;
+            },
+            Instruction::Dec => {
+                tape[head] = tape[head].wrapping_sub(1);
+                ip += 1;
+            },
+            Instruction::Next => {
+                head = move_head(&mut tape, head, 1);
+                ip += 1;
+            },
+            Instruction::Prev => {
+                head = move_head(&mut tape, head, -1);
+                ip += 1;
+            },
+            Instruction::Put => {
+                if output.write_all(&[tape[head]]).is_err() {
+                    return ExitStatus::WriteError;
+                }
+                ip += 1;
+            },
+            Instruction::Get => {
+                let mut byte = [0u8];
+                match input.read(&mut byte) {
+                    Ok(1) => {
+                        tape[head] = byte[0];
+                        ip += 1;
+                    },
+                    _ => return ExitStatus::InputEof,
+                }
+            },
+            Instruction::Jz(target) => {
+                ip = if tape[head] == 0 { target } else { ip + 1 };
+            },
+            Instruction::Jnz(target) => {
+                ip = if tape[head] != 0 { target } else { ip + 1 };
+            },
+            Instruction::Halt => return ExitStatus::Halted,
+        }
+    }
+
+    ExitStatus::Halted
+}
+
+/// Moves `head` by `delta`, growing `tape` at whichever end it walks off.
+/// Unlike the JIT runtime's chunked reallocation, the interpreter's tape is
+/// a plain `Vec`, so growing it is just `resize` (forward) or prepending
+/// zeroed cells (backward).
+fn move_head(tape: &mut Vec<u8>, head: usize, delta: isize) -> usize {
+    if delta >= 0 {
+        let new_head = head + delta as usize;
+        if new_head >= tape.len() {
+            tape.resize(new_head + 1, 0);
+        }
+        new_head
+    } else {
+        let back = (-delta) as usize;
+        if back > head {
+            let shortfall = back - head;
+            let mut grown = vec![0u8; shortfall];
+            grown.append(tape);
+            *tape = grown;
+            0
+        } else {
+            head - back
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::Instruction;
+
+    #[test]
+    fn put_writes_the_current_cell() {
+        // `+.` (`Add(1)` after folding), i.e. cell 0 goes from 0 to 1.
+        let program = Program {
+            code: vec![Instruction::Add(1), Instruction::Put, Instruction::Halt],
+        };
+        let mut input: &[u8] = &[];
+        let mut output = Vec::new();
+        let status = run(&program, 1000, &mut input, &mut output);
+        assert_eq!(status, ExitStatus::Halted);
+        assert_eq!(output, vec![1]);
+    }
+
+    #[test]
+    fn get_stores_the_input_byte() {
+        let program = Program {
+            code: vec![Instruction::Get, Instruction::Put, Instruction::Halt],
+        };
+        let mut input: &[u8] = &[65];
+        let mut output = Vec::new();
+        let status = run(&program, 1000, &mut input, &mut output);
+        assert_eq!(status, ExitStatus::Halted);
+        assert_eq!(output, vec![65]);
+    }
+
+    #[test]
+    fn get_past_eof_reports_input_eof() {
+        let program = Program { code: vec![Instruction::Get, Instruction::Halt] };
+        let mut input: &[u8] = &[];
+        let mut output = Vec::new();
+        let status = run(&program, 1000, &mut input, &mut output);
+        assert_eq!(status, ExitStatus::InputEof);
+    }
+
+    #[test]
+    fn put_failure_reports_write_error() {
+        struct FailingWriter;
+        impl Write for FailingWriter {
+            fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("disk on fire"))
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let program = Program { code: vec![Instruction::Put, Instruction::Halt] };
+        let mut input: &[u8] = &[];
+        let mut output = FailingWriter;
+        let status = run(&program, 1000, &mut input, &mut output);
+        assert_eq!(status, ExitStatus::WriteError);
+    }
+
+    #[test]
+    fn non_terminating_loop_exhausts_its_budget() {
+        // `+[]`: an infinite loop once the cell is non-zero.
+        let program = Program {
+            code: vec![
+                Instruction::Add(1),
+                Instruction::Jz(3),
+                Instruction::Jnz(1),
+                Instruction::Halt,
+            ],
+        };
+        let mut input: &[u8] = &[];
+        let mut output = Vec::new();
+        let status = run(&program, 100, &mut input, &mut output);
+        assert_eq!(status, ExitStatus::BudgetExhausted);
+    }
+
+    #[test]
+    fn move_grows_the_tape_in_both_directions() {
+        // `<<<>>>>.` on a single-cell tape: walk left past the start
+        // (prepending cells), then right past the original end (appending
+        // cells), and read the fresh zero cell there.
+        let program = Program {
+            code: vec![
+                Instruction::Move(-3),
+                Instruction::Move(4),
+                Instruction::Put,
+                Instruction::Halt,
+            ],
+        };
+        let mut input: &[u8] = &[];
+        let mut output = Vec::new();
+        let status = run(&program, 1000, &mut input, &mut output);
+        assert_eq!(status, ExitStatus::Halted);
+        assert_eq!(output, vec![0]);
+    }
+}
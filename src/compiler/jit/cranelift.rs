@@ -0,0 +1,569 @@
+//! Portable JIT backend built on Cranelift, used on every target the
+//! hand-emitted [`super::x86_64`] backend doesn't cover (aarch64, macOS,
+//! Windows, ...).
+//!
+//! Each [`Instruction`] lowers to a handful of Cranelift IR ops instead of
+//! raw machine code bytes; `cranelift-jit` takes care of register
+//! allocation and emitting the actual native instructions. The tape base
+//! pointer, its current capacity and the head index are tracked as
+//! Cranelift [`Variable`]s rather than fixed registers, so
+//! `cranelift-frontend` builds the SSA form (and the phis at loop headers)
+//! for us instead of us doing it by hand like in the x86-64 backend.
+
+use super::super::ExitStatus;
+use super::runtime::{self, IoContext};
+use crate::ir::{Instruction, Program};
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{
+    types,
+    AbiParam,
+    Block,
+    FuncRef,
+    InstBuilder,
+    MemFlags,
+    Value,
+};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Linkage, Module, ModuleError};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+/// `bf_main`'s signature: `(ctx, initial_chunk, initial_capacity) -> code`.
+///
+/// `code` mirrors [`ExitStatus`]'s `#[repr(i32)]` discriminants (`0`
+/// halted, `1` input EOF/error, `2` write error); codegen never emits a
+/// `3` (`BudgetExhausted` is [`crate::compiler::interp`]-only), so
+/// [`Executable::run`] can convert it infallibly.
+type MainFn = unsafe extern "C" fn(*mut IoContext, *mut u8, i64) -> i32;
+
+fn module_err(err: ModuleError) -> super::Error {
+    super::Error::Codegen(err.to_string())
+}
+
+/// Lowers `program` through Cranelift and finalizes it into callable code.
+pub fn compile(program: &Program) -> Result<Executable, super::Error> {
+    let mut flag_builder = settings::builder();
+    flag_builder
+        .set("is_pic", "false")
+        .map_err(|err| super::Error::Codegen(err.to_string()))?;
+    let isa_builder = cranelift_native::builder()
+        .map_err(|err| super::Error::Codegen(err.to_string()))?;
+    let isa = isa_builder
+        .finish(settings::Flags::new(flag_builder))
+        .map_err(|err| super::Error::Codegen(err.to_string()))?;
+
+    let mut jit_builder = JITBuilder::with_isa(isa, default_libcall_names());
+    jit_builder.symbol("bf_grow_next", runtime::grow_next as *const u8);
+    jit_builder.symbol("bf_grow_prev", runtime::grow_prev as *const u8);
+    jit_builder.symbol("bf_put", runtime::put as *const u8);
+    jit_builder.symbol("bf_get", runtime::get as *const u8);
+    let mut module = JITModule::new(jit_builder);
+    let ptr_ty = module.target_config().pointer_type();
+
+    let mut sig_grow = module.make_signature();
+    sig_grow.params.push(AbiParam::new(ptr_ty));
+    sig_grow.params.push(AbiParam::new(types::I64));
+    sig_grow.returns.push(AbiParam::new(ptr_ty));
+    let grow_next_id = module
+        .declare_function("bf_grow_next", Linkage::Import, &sig_grow)
+        .map_err(module_err)?;
+    let grow_prev_id = module
+        .declare_function("bf_grow_prev", Linkage::Import, &sig_grow)
+        .map_err(module_err)?;
+
+    let mut sig_put = module.make_signature();
+    sig_put.params.push(AbiParam::new(ptr_ty));
+    sig_put.params.push(AbiParam::new(types::I16));
+    sig_put.returns.push(AbiParam::new(types::I8));
+    let put_id = module
+        .declare_function("bf_put", Linkage::Import, &sig_put)
+        .map_err(module_err)?;
+
+    let mut sig_get = module.make_signature();
+    sig_get.params.push(AbiParam::new(ptr_ty));
+    sig_get.returns.push(AbiParam::new(types::I16));
+    let get_id = module
+        .declare_function("bf_get", Linkage::Import, &sig_get)
+        .map_err(module_err)?;
+
+    let mut sig_main = module.make_signature();
+    sig_main.params.push(AbiParam::new(ptr_ty));
+    sig_main.params.push(AbiParam::new(ptr_ty));
+    sig_main.params.push(AbiParam::new(types::I64));
+    sig_main.returns.push(AbiParam::new(types::I32));
+    let main_id = module
+        .declare_function("bf_main", Linkage::Export, &sig_main)
+        .map_err(module_err)?;
+
+    let mut ctx = module.make_context();
+    ctx.func.signature = sig_main;
+    let mut fn_builder_ctx = FunctionBuilderContext::new();
+    {
+        let mut builder = FunctionBuilder::new(&mut ctx.func, &mut fn_builder_ctx);
+        let grow_next_ref =
+            module.declare_func_in_func(grow_next_id, builder.func);
+        let grow_prev_ref =
+            module.declare_func_in_func(grow_prev_id, builder.func);
+        let put_ref = module.declare_func_in_func(put_id, builder.func);
+        let get_ref = module.declare_func_in_func(get_id, builder.func);
+
+        Lowering::new(
+            &mut builder,
+            ptr_ty,
+            grow_next_ref,
+            grow_prev_ref,
+            put_ref,
+            get_ref,
+        )
+        .lower(program);
+        builder.finalize();
+    }
+
+    module.define_function(main_id, &mut ctx).map_err(module_err)?;
+    module.clear_context(&mut ctx);
+    module.finalize_definitions().map_err(module_err)?;
+
+    let code_ptr = module.get_finalized_function(main_id);
+    let entry = unsafe { std::mem::transmute::<*const u8, MainFn>(code_ptr) };
+
+    Ok(Executable { module: Some(module), entry })
+}
+
+/// The tape base pointer, its capacity and the head index, each kept as a
+/// Cranelift variable so `cranelift-frontend` threads them through phis at
+/// block joins for us.
+struct Vars {
+    tape: Variable,
+    cap: Variable,
+    idx: Variable,
+}
+
+/// Per-compilation lowering state: mirrors [`super::x86_64::Compiler`] but
+/// emits Cranelift IR into a [`FunctionBuilder`] instead of raw bytes into a
+/// `Vec<u8>`. IR labels map to [`Block`]s lazily, the same way the x86-64
+/// backend's `labels` map resolves jump placeholders, except Cranelift lets
+/// us leave every block unsealed until the whole function is lowered.
+struct Lowering<'a, 'b> {
+    builder: &'a mut FunctionBuilder<'b>,
+    blocks: HashMap<usize, Block>,
+    vars: Vars,
+    ctx_param: Value,
+    grow_next_ref: FuncRef,
+    grow_prev_ref: FuncRef,
+    put_ref: FuncRef,
+    get_ref: FuncRef,
+}
+
+impl<'a, 'b> Lowering<'a, 'b> {
+    fn new(
+        builder: &'a mut FunctionBuilder<'b>,
+        ptr_ty: types::Type,
+        grow_next_ref: FuncRef,
+        grow_prev_ref: FuncRef,
+        put_ref: FuncRef,
+        get_ref: FuncRef,
+    ) -> Self {
+        let entry_block = builder.create_block();
+        builder.append_block_params_for_function_params(entry_block);
+        builder.switch_to_block(entry_block);
+
+        let params = builder.block_params(entry_block);
+        let ctx_param = params[0];
+        let tape_param = params[1];
+        let cap_param = params[2];
+
+        let vars = Vars {
+            tape: Variable::new(0),
+            cap: Variable::new(1),
+            idx: Variable::new(2),
+        };
+        builder.declare_var(vars.tape, ptr_ty);
+        builder.declare_var(vars.cap, types::I64);
+        builder.declare_var(vars.idx, types::I64);
+        builder.def_var(vars.tape, tape_param);
+        builder.def_var(vars.cap, cap_param);
+        let zero = builder.ins().iconst(types::I64, 0);
+        builder.def_var(vars.idx, zero);
+
+        let mut blocks = HashMap::new();
+        blocks.insert(0, entry_block);
+
+        Self {
+            builder,
+            blocks,
+            vars,
+            ctx_param,
+            grow_next_ref,
+            grow_prev_ref,
+            put_ref,
+            get_ref,
+        }
+    }
+
+    fn block_for(&mut self, ir_label: usize) -> Block {
+        let builder = &mut self.builder;
+        *self.blocks.entry(ir_label).or_insert_with(|| builder.create_block())
+    }
+
+    fn load_cell(&mut self) -> Value {
+        let tape = self.builder.use_var(self.vars.tape);
+        let idx = self.builder.use_var(self.vars.idx);
+        let addr = self.builder.ins().iadd(tape, idx);
+        self.builder.ins().load(types::I8, MemFlags::trusted(), addr, 0)
+    }
+
+    fn store_cell(&mut self, value: Value) {
+        let tape = self.builder.use_var(self.vars.tape);
+        let idx = self.builder.use_var(self.vars.idx);
+        let addr = self.builder.ins().iadd(tape, idx);
+        self.builder.ins().store(MemFlags::trusted(), value, addr, 0);
+    }
+
+    fn write_add(&mut self, delta: i64) {
+        let cell = self.load_cell();
+        let updated = self.builder.ins().iadd_imm(cell, delta);
+        self.store_cell(updated);
+    }
+
+    fn write_set_zero(&mut self) {
+        let zero = self.builder.ins().iconst(types::I8, 0);
+        self.store_cell(zero);
+    }
+
+    /// `Move(delta)`: folded run of `>`/`<`. Unlike a single `Next`/`Prev`,
+    /// `delta` may cross more than one [`runtime::TAPE_CHUNK_SIZE`]
+    /// boundary, so growth is a real loop rather than one `brif`.
+    fn write_move(&mut self, delta: isize) -> Block {
+        if delta > 0 {
+            self.write_move_forward(delta as i64)
+        } else {
+            self.write_move_backward((-delta) as i64)
+        }
+    }
+
+    fn write_move_forward(&mut self, amount: i64) -> Block {
+        let header = self.builder.create_block();
+        let grow_block = self.builder.create_block();
+        let done_block = self.builder.create_block();
+        self.builder.ins().jump(header, &[]);
+
+        self.builder.switch_to_block(header);
+        let idx_val = self.builder.use_var(self.vars.idx);
+        let cap_val = self.builder.use_var(self.vars.cap);
+        let remaining = self.builder.ins().isub(cap_val, idx_val);
+        // Strictly greater, not >=: the head must end up `< cap` after the
+        // move, so a chunk that leaves exactly `amount` remaining cells
+        // still needs one more grow.
+        let has_room = self.builder.ins().icmp_imm(
+            IntCC::SignedGreaterThan,
+            remaining,
+            amount,
+        );
+        self.builder.ins().brif(has_room, done_block, &[], grow_block, &[]);
+
+        self.builder.switch_to_block(grow_block);
+        let tape_val = self.builder.use_var(self.vars.tape);
+        let cap_val = self.builder.use_var(self.vars.cap);
+        let call = self.builder.ins().call(self.grow_next_ref, &[tape_val, cap_val]);
+        let new_tape = self.builder.inst_results(call)[0];
+        self.builder.def_var(self.vars.tape, new_tape);
+        let new_cap =
+            self.builder.ins().iadd_imm(cap_val, runtime::TAPE_CHUNK_SIZE as i64);
+        self.builder.def_var(self.vars.cap, new_cap);
+        self.builder.ins().jump(header, &[]);
+
+        self.builder.switch_to_block(done_block);
+        let idx_val = self.builder.use_var(self.vars.idx);
+        let new_idx = self.builder.ins().iadd_imm(idx_val, amount);
+        self.builder.def_var(self.vars.idx, new_idx);
+        done_block
+    }
+
+    fn write_move_backward(&mut self, amount: i64) -> Block {
+        let header = self.builder.create_block();
+        let grow_block = self.builder.create_block();
+        let done_block = self.builder.create_block();
+        self.builder.ins().jump(header, &[]);
+
+        self.builder.switch_to_block(header);
+        let idx_val = self.builder.use_var(self.vars.idx);
+        let has_room = self.builder.ins().icmp_imm(
+            IntCC::SignedGreaterThanOrEqual,
+            idx_val,
+            amount,
+        );
+        self.builder.ins().brif(has_room, done_block, &[], grow_block, &[]);
+
+        self.builder.switch_to_block(grow_block);
+        let tape_val = self.builder.use_var(self.vars.tape);
+        let cap_val = self.builder.use_var(self.vars.cap);
+        let call = self.builder.ins().call(self.grow_prev_ref, &[tape_val, cap_val]);
+        let new_tape = self.builder.inst_results(call)[0];
+        self.builder.def_var(self.vars.tape, new_tape);
+        let idx_val = self.builder.use_var(self.vars.idx);
+        let grown_idx =
+            self.builder.ins().iadd_imm(idx_val, runtime::TAPE_CHUNK_SIZE as i64);
+        self.builder.def_var(self.vars.idx, grown_idx);
+        let new_cap =
+            self.builder.ins().iadd_imm(cap_val, runtime::TAPE_CHUNK_SIZE as i64);
+        self.builder.def_var(self.vars.cap, new_cap);
+        self.builder.ins().jump(header, &[]);
+
+        self.builder.switch_to_block(done_block);
+        let idx_val = self.builder.use_var(self.vars.idx);
+        let new_idx = self.builder.ins().iadd_imm(idx_val, -amount);
+        self.builder.def_var(self.vars.idx, new_idx);
+        done_block
+    }
+
+    /// `>`: advances the head, growing the tape into a fresh chunk first if
+    /// the head is about to walk off the end of the current one.
+    fn write_next(&mut self) -> Block {
+        let idx_val = self.builder.use_var(self.vars.idx);
+        let cap_val = self.builder.use_var(self.vars.cap);
+        // Grow before the head would land *on* `cap`, not after: checking
+        // `idx == cap` lets the post-increment head sit one past the last
+        // mapped cell whenever it was already at `cap - 1`.
+        let next_idx = self.builder.ins().iadd_imm(idx_val, 1);
+        let at_end =
+            self.builder.ins().icmp(IntCC::SignedGreaterThanOrEqual, next_idx, cap_val);
+        let grow_block = self.builder.create_block();
+        let cont_block = self.builder.create_block();
+        self.builder.ins().brif(at_end, grow_block, &[], cont_block, &[]);
+
+        self.builder.switch_to_block(grow_block);
+        let tape_val = self.builder.use_var(self.vars.tape);
+        let call = self.builder.ins().call(self.grow_next_ref, &[tape_val, cap_val]);
+        let new_tape = self.builder.inst_results(call)[0];
+        self.builder.def_var(self.vars.tape, new_tape);
+        let new_cap =
+            self.builder.ins().iadd_imm(cap_val, runtime::TAPE_CHUNK_SIZE as i64);
+        self.builder.def_var(self.vars.cap, new_cap);
+        self.builder.ins().jump(cont_block, &[]);
+
+        self.builder.switch_to_block(cont_block);
+        let idx_val = self.builder.use_var(self.vars.idx);
+        let new_idx = self.builder.ins().iadd_imm(idx_val, 1);
+        self.builder.def_var(self.vars.idx, new_idx);
+        cont_block
+    }
+
+    /// `<`: symmetric to [`Self::write_next`], growing backwards.
+    fn write_prev(&mut self) -> Block {
+        let idx_val = self.builder.use_var(self.vars.idx);
+        let at_start = self.builder.ins().icmp_imm(IntCC::Equal, idx_val, 0);
+        let grow_block = self.builder.create_block();
+        let cont_block = self.builder.create_block();
+        self.builder.ins().brif(at_start, grow_block, &[], cont_block, &[]);
+
+        self.builder.switch_to_block(grow_block);
+        let tape_val = self.builder.use_var(self.vars.tape);
+        let cap_val = self.builder.use_var(self.vars.cap);
+        let call = self.builder.ins().call(self.grow_prev_ref, &[tape_val, cap_val]);
+        let new_tape = self.builder.inst_results(call)[0];
+        self.builder.def_var(self.vars.tape, new_tape);
+        let grown_idx =
+            self.builder.ins().iadd_imm(idx_val, runtime::TAPE_CHUNK_SIZE as i64);
+        self.builder.def_var(self.vars.idx, grown_idx);
+        let new_cap =
+            self.builder.ins().iadd_imm(cap_val, runtime::TAPE_CHUNK_SIZE as i64);
+        self.builder.def_var(self.vars.cap, new_cap);
+        self.builder.ins().jump(cont_block, &[]);
+
+        self.builder.switch_to_block(cont_block);
+        let idx_val = self.builder.use_var(self.vars.idx);
+        let new_idx = self.builder.ins().iadd_imm(idx_val, -1);
+        self.builder.def_var(self.vars.idx, new_idx);
+        cont_block
+    }
+
+    /// `.`: writes the current cell out; an I/O error exits the function
+    /// early with code `2`.
+    fn write_put(&mut self) -> Block {
+        let cell = self.load_cell();
+        let value = self.builder.ins().uextend(types::I16, cell);
+        let call = self.builder.ins().call(self.put_ref, &[self.ctx_param, value]);
+        let result = self.builder.inst_results(call)[0];
+        let is_err = self.builder.ins().icmp_imm(IntCC::SignedLessThan, result, 0);
+        let err_block = self.builder.create_block();
+        let ok_block = self.builder.create_block();
+        self.builder.ins().brif(is_err, err_block, &[], ok_block, &[]);
+
+        self.builder.switch_to_block(err_block);
+        let write_error = self.builder.ins().iconst(types::I32, 2);
+        self.builder.ins().return_(&[write_error]);
+
+        self.builder.switch_to_block(ok_block);
+        ok_block
+    }
+
+    /// `,`: grows the tape the same way [`Self::write_next`] does if the
+    /// head sits exactly on the chunk boundary (`idx == cap`) before it's
+    /// indexed, then reads one byte; EOF/error exits the function early
+    /// with code `1`.
+    fn write_get(&mut self) -> Block {
+        let idx_val = self.builder.use_var(self.vars.idx);
+        let cap_val = self.builder.use_var(self.vars.cap);
+        let at_end = self.builder.ins().icmp(IntCC::Equal, idx_val, cap_val);
+        let grow_block = self.builder.create_block();
+        let read_block = self.builder.create_block();
+        self.builder.ins().brif(at_end, grow_block, &[], read_block, &[]);
+
+        self.builder.switch_to_block(grow_block);
+        let tape_val = self.builder.use_var(self.vars.tape);
+        let call = self.builder.ins().call(self.grow_next_ref, &[tape_val, cap_val]);
+        let new_tape = self.builder.inst_results(call)[0];
+        self.builder.def_var(self.vars.tape, new_tape);
+        let new_cap =
+            self.builder.ins().iadd_imm(cap_val, runtime::TAPE_CHUNK_SIZE as i64);
+        self.builder.def_var(self.vars.cap, new_cap);
+        self.builder.ins().jump(read_block, &[]);
+
+        self.builder.switch_to_block(read_block);
+        let call = self.builder.ins().call(self.get_ref, &[self.ctx_param]);
+        let result = self.builder.inst_results(call)[0];
+        let is_err = self.builder.ins().icmp_imm(IntCC::SignedLessThan, result, 0);
+        let err_block = self.builder.create_block();
+        let ok_block = self.builder.create_block();
+        self.builder.ins().brif(is_err, err_block, &[], ok_block, &[]);
+
+        self.builder.switch_to_block(err_block);
+        let input_eof = self.builder.ins().iconst(types::I32, 1);
+        self.builder.ins().return_(&[input_eof]);
+
+        self.builder.switch_to_block(ok_block);
+        let byte = self.builder.ins().ireduce(types::I8, result);
+        self.store_cell(byte);
+        ok_block
+    }
+
+    fn lower(&mut self, program: &Program) {
+        let last_label = program.code.len();
+        let mut current_block = self.blocks[&0];
+
+        for ir_label in 0 .. last_label {
+            let label_block = self.block_for(ir_label);
+            if label_block != current_block {
+                self.builder.ins().jump(label_block, &[]);
+                self.builder.switch_to_block(label_block);
+                current_block = label_block;
+            }
+
+            current_block = match program.code[ir_label] {
+                Instruction::Add(delta) => {
+                    self.write_add(delta as i64);
+                    current_block
+                },
+                Instruction::Move(0) => current_block,
+                Instruction::Move(delta) => self.write_move(delta),
+                Instruction::SetZero => {
+                    self.write_set_zero();
+                    current_block
+                },
+                Instruction::Inc => {
+                    self.write_add(1);
+                    current_block
+                },
+                Instruction::Dec => {
+                    self.write_add(-1);
+                    current_block
+                },
+                Instruction::Next => self.write_next(),
+                Instruction::Prev => self.write_prev(),
+                Instruction::Get => self.write_get(),
+                Instruction::Put => self.write_put(),
+                Instruction::Jz(target) => {
+                    let target_block = self.block_for(target);
+                    let fallthrough = self.block_for(ir_label + 1);
+                    let cell = self.load_cell();
+                    let cell32 = self.builder.ins().uextend(types::I32, cell);
+                    let is_zero =
+                        self.builder.ins().icmp_imm(IntCC::Equal, cell32, 0);
+                    self.builder.ins().brif(
+                        is_zero,
+                        target_block,
+                        &[],
+                        fallthrough,
+                        &[],
+                    );
+                    self.builder.switch_to_block(fallthrough);
+                    fallthrough
+                },
+                Instruction::Jnz(target) => {
+                    let target_block = self.block_for(target);
+                    let fallthrough = self.block_for(ir_label + 1);
+                    let cell = self.load_cell();
+                    let cell32 = self.builder.ins().uextend(types::I32, cell);
+                    let is_nonzero =
+                        self.builder.ins().icmp_imm(IntCC::NotEqual, cell32, 0);
+                    self.builder.ins().brif(
+                        is_nonzero,
+                        target_block,
+                        &[],
+                        fallthrough,
+                        &[],
+                    );
+                    self.builder.switch_to_block(fallthrough);
+                    fallthrough
+                },
+                Instruction::Halt => {
+                    let halt_block = self.block_for(last_label);
+                    self.builder.ins().jump(halt_block, &[]);
+                    let dead_block = self.builder.create_block();
+                    self.builder.switch_to_block(dead_block);
+                    dead_block
+                },
+            };
+        }
+
+        let halt_block = self.block_for(last_label);
+        if halt_block != current_block {
+            self.builder.ins().jump(halt_block, &[]);
+        }
+        self.builder.switch_to_block(halt_block);
+        let halted = self.builder.ins().iconst(types::I32, 0);
+        self.builder.ins().return_(&[halted]);
+
+        self.builder.seal_all_blocks();
+    }
+}
+
+/// An executable produced by the Cranelift backend. Owns the [`JITModule`]
+/// backing `entry`'s code, so it must outlive every call through it; the
+/// module's executable mapping is released on [`Drop`].
+pub struct Executable {
+    module: Option<JITModule>,
+    entry: MainFn,
+}
+
+impl Executable {
+    /// Runs the program to completion, threading `input`/`output` through
+    /// the same [`IoContext`] the `bf_get`/`bf_put` host calls use.
+    pub fn run(&self, input: &mut dyn Read, output: &mut dyn Write) -> ExitStatus {
+        let mut io = IoContext { input, output };
+        let chunk_ptr = runtime::alloc_tape(runtime::TAPE_CHUNK_SIZE);
+        let code = unsafe {
+            (self.entry)(
+                &mut io as *mut IoContext,
+                chunk_ptr,
+                runtime::TAPE_CHUNK_SIZE as i64,
+            )
+        };
+        match code {
+            0 => ExitStatus::Halted,
+            1 => ExitStatus::InputEof,
+            2 => ExitStatus::WriteError,
+            _ => unreachable!("bf_main returned unknown exit code {code}"),
+        }
+    }
+}
+
+impl Drop for Executable {
+    fn drop(&mut self) {
+        if let Some(module) = self.module.take() {
+            unsafe { module.free_memory() };
+        }
+    }
+}
@@ -0,0 +1,343 @@
+//! Ahead-of-time compilation: writes a standalone Linux x86-64 ELF
+//! executable instead of mapping the JITed code into the current process.
+//!
+//! A shipped binary has no host process to call back into, so this backend
+//! reuses [`x86_64::Compiler`]'s two-pass emitter in
+//! [`RuntimeLinkage::Stub`](x86_64::RuntimeLinkage::Stub) mode: every
+//! `runtime::put`/`get`/`grow_next`/`grow_prev` call site becomes a relative
+//! call to one of four small machine-code stubs, statically linked in right
+//! after the program's own code, that do the same job directly through
+//! `read`/`write`/`mmap` syscalls. `write_enter`'s prologue also expects its
+//! registers set up by a C caller, which a freestanding ELF entry point
+//! doesn't have, so a short setup stub mmaps the initial tape chunk and
+//! loads the registers `write_enter` expects before falling straight through
+//! into it; `e_entry` points at that setup stub.
+
+use super::x86_64::{self, Compiler, RuntimeLinkage};
+use crate::ir::Program;
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+const SYS_MMAP: u32 = 9;
+const PROT_READ_WRITE: u32 = 0x3;
+const MAP_PRIVATE_ANONYMOUS: u32 = 0x22;
+
+const XOR_EAX_TO_EAX: [u8; 2] = [0x31, 0xc0];
+const XOR_EDI_TO_EDI: [u8; 2] = [0x31, 0xff];
+const XOR_R9D_TO_R9D: [u8; 3] = [0x45, 0x31, 0xc9];
+const MOV_IMM32_TO_EAX: [u8; 1] = [0xb8];
+const MOV_IMM32_TO_EDI: [u8; 1] = [0xbf];
+const MOV_IMM32_TO_ESI: [u8; 1] = [0xbe];
+const MOV_IMM32_TO_EDX: [u8; 1] = [0xba];
+const MOV_IMM32_TO_R10D: [u8; 2] = [0x41, 0xba];
+const MOVABS_NEG1_TO_R8: [u8; 2] = [0x49, 0xb8];
+const MOV_RSP_TO_RSI: [u8; 3] = [0x48, 0x89, 0xe6];
+const MOV_RSI_TO_RAX: [u8; 3] = [0x48, 0x89, 0xf0];
+const MOV_RAX_TO_RSI: [u8; 3] = [0x48, 0x89, 0xc6];
+const MOV_RAX_TO_RDI: [u8; 3] = [0x48, 0x89, 0xc7];
+const ADD_IMM32_TO_RAX: [u8; 2] = [0x48, 0x05];
+const LEA_RAX_PLUS_IMM32_TO_RDI: [u8; 3] = [0x48, 0x8d, 0xb8];
+const SUB_8_FROM_RSP: [u8; 4] = [0x48, 0x83, 0xec, 0x08];
+const ADD_8_TO_RSP: [u8; 4] = [0x48, 0x83, 0xc4, 0x08];
+const MOVZX_BYTE_MEM_RSP_TO_EAX: [u8; 4] = [0x0f, 0xb6, 0x04, 0x24];
+const TEST_RAX_WITH_RAX: [u8; 3] = [0x48, 0x85, 0xc0];
+const JS_REL32: [u8; 2] = [0x0f, 0x88];
+const JLE_REL32: [u8; 2] = [0x0f, 0x8e];
+const SYSCALL: [u8; 2] = [0x0f, 0x05];
+const RET: [u8; 1] = [0xc3];
+const CLD: [u8; 1] = [0xfc];
+const REP_MOVSB: [u8; 2] = [0xf3, 0xa4];
+const PUSH_RDI: [u8; 1] = [0x57];
+const PUSH_RSI: [u8; 1] = [0x56];
+const PUSH_RAX: [u8; 1] = [0x50];
+const POP_RAX: [u8; 1] = [0x58];
+const POP_RSI: [u8; 1] = [0x5e];
+const POP_RCX: [u8; 1] = [0x59];
+
+const TAPE_CHUNK_SIZE: u32 = super::runtime::TAPE_CHUNK_SIZE as u32;
+
+/// Linux x86-64 `ET_EXEC` binaries conventionally load at this base; no
+/// dynamic linker or ASLR is involved here, so a fixed address is fine.
+const LOAD_BASE: u64 = 0x400000;
+const ELF_HEADER_SIZE: u64 = 64;
+const PROGRAM_HEADER_SIZE: u64 = 56;
+const HEADERS_SIZE: u64 = ELF_HEADER_SIZE + PROGRAM_HEADER_SIZE;
+
+/// Compiles `program` into a standalone Linux x86-64 ELF executable at
+/// `path`, instead of mapping it into the current process.
+pub fn compile_to_elf(program: &Program, path: &Path) -> Result<(), super::Error> {
+    if !x86_64::TARGET_SUPPORTED {
+        Err(super::Error::UnsupportedTarget)?;
+    }
+
+    let program = crate::ir::optimize::run(program);
+    let code = emit_code(&program)?;
+
+    let mut file = Vec::with_capacity(HEADERS_SIZE as usize + code.len());
+    write_elf_header(&mut file);
+    write_program_header(&mut file, code.len() as u64);
+    file.extend_from_slice(&code);
+
+    fs::write(path, &file).map_err(io_err)?;
+    let mut perms = fs::metadata(path).map_err(io_err)?.permissions();
+    perms.set_mode(0o755);
+    fs::set_permissions(path, perms).map_err(io_err)?;
+    Ok(())
+}
+
+fn io_err(_err: io::Error) -> super::Error {
+    super::Error::AllocError
+}
+
+/// Builds the process image: a tiny freestanding setup stub (mmaps the
+/// initial tape, arranges registers the way `write_enter` expects), the
+/// program's own code, then the four syscall stubs it calls into.
+fn emit_code(program: &Program) -> Result<Vec<u8>, super::Error> {
+    let mut compiler = Compiler::new(RuntimeLinkage::Stub);
+    let last_ir_label = program.code.len();
+
+    write_process_entry(&mut compiler);
+    compiler.write_enter();
+    compiler.first_pass(program);
+    compiler.write_leave_exit(last_ir_label);
+
+    compiler.def_label(x86_64::STUB_PUT, 0);
+    write_put_stub(&mut compiler);
+    compiler.def_label(x86_64::STUB_GET, 0);
+    write_get_stub(&mut compiler);
+    compiler.def_label(x86_64::STUB_GROW_NEXT, 0);
+    write_grow_next_stub(&mut compiler);
+    compiler.def_label(x86_64::STUB_GROW_PREV, 0);
+    write_grow_prev_stub(&mut compiler);
+
+    compiler.second_pass()?;
+    Ok(compiler.into_bytes())
+}
+
+/// Replaces the caller a JIT'd program would normally have: mmaps the
+/// initial tape chunk and loads `rdi`/`rsi`/`rdx` with the IO context (unused
+/// in AOT mode, since there's no `IoContext` to thread through), tape base
+/// pointer and tape capacity, then falls straight through into `write_enter`.
+fn write_process_entry(compiler: &mut Compiler) {
+    compiler.emit_bytes(&XOR_EDI_TO_EDI); // addr = NULL
+    compiler.emit_bytes(&MOV_IMM32_TO_ESI);
+    compiler.emit_bytes(&TAPE_CHUNK_SIZE.to_le_bytes()); // length
+    compiler.emit_bytes(&MOV_IMM32_TO_EDX);
+    compiler.emit_bytes(&PROT_READ_WRITE.to_le_bytes());
+    compiler.emit_bytes(&MOV_IMM32_TO_R10D);
+    compiler.emit_bytes(&MAP_PRIVATE_ANONYMOUS.to_le_bytes());
+    compiler.emit_bytes(&MOVABS_NEG1_TO_R8);
+    compiler.emit_bytes(&(-1i64).to_le_bytes());
+    compiler.emit_bytes(&XOR_R9D_TO_R9D); // offset = 0
+    compiler.emit_bytes(&MOV_IMM32_TO_EAX);
+    compiler.emit_bytes(&SYS_MMAP.to_le_bytes());
+    compiler.emit_bytes(&SYSCALL); // rax = tape base
+
+    compiler.emit_bytes(&MOV_RAX_TO_RSI); // rsi = tape base
+    compiler.emit_bytes(&XOR_EDI_TO_EDI); // rdi = ctx = NULL
+    compiler.emit_bytes(&MOV_IMM32_TO_EDX);
+    compiler.emit_bytes(&TAPE_CHUNK_SIZE.to_le_bytes()); // rdx = cap
+}
+
+/// `put(value)`: write one byte to stdout. Mirrors [`super::runtime::put`]'s
+/// calling convention (`rdi` = ctx, unused here; `rsi` = value, low byte
+/// live) and its "negative `al` means error" return convention.
+fn write_put_stub(compiler: &mut Compiler) {
+    compiler.emit_bytes(&PUSH_RSI); // the value byte now lives at [rsp]
+    compiler.emit_bytes(&MOV_IMM32_TO_EAX);
+    compiler.emit_bytes(&1u32.to_le_bytes()); // sys_write
+    compiler.emit_bytes(&MOV_IMM32_TO_EDI);
+    compiler.emit_bytes(&1u32.to_le_bytes()); // fd = stdout
+    compiler.emit_bytes(&MOV_RSP_TO_RSI);
+    compiler.emit_bytes(&MOV_IMM32_TO_EDX);
+    compiler.emit_bytes(&1u32.to_le_bytes()); // count = 1
+    compiler.emit_bytes(&SYSCALL);
+    compiler.emit_bytes(&POP_RCX);
+    compiler.emit_bytes(&TEST_RAX_WITH_RAX);
+    compiler.emit_bytes(&JS_REL32);
+    compiler.make_placeholder(x86_64::STUB_PUT, 1);
+    compiler.emit_bytes(&XOR_EAX_TO_EAX);
+    compiler.emit_bytes(&RET);
+
+    compiler.def_label(x86_64::STUB_PUT, 1);
+    compiler.emit_bytes(&MOV_IMM32_TO_EAX);
+    compiler.emit_bytes(&(-1i32).to_le_bytes());
+    compiler.emit_bytes(&RET);
+}
+
+/// `get()`: read one byte from stdin. Mirrors [`super::runtime::get`]'s
+/// "negative `ax` means EOF/error" return convention.
+fn write_get_stub(compiler: &mut Compiler) {
+    compiler.emit_bytes(&SUB_8_FROM_RSP);
+    compiler.emit_bytes(&XOR_EAX_TO_EAX); // sys_read = 0
+    compiler.emit_bytes(&XOR_EDI_TO_EDI); // fd = stdin
+    compiler.emit_bytes(&MOV_RSP_TO_RSI);
+    compiler.emit_bytes(&MOV_IMM32_TO_EDX);
+    compiler.emit_bytes(&1u32.to_le_bytes());
+    compiler.emit_bytes(&SYSCALL);
+    compiler.emit_bytes(&TEST_RAX_WITH_RAX);
+    compiler.emit_bytes(&JLE_REL32); // 0 bytes (EOF) or negative (error)
+    compiler.make_placeholder(x86_64::STUB_GET, 1);
+    compiler.emit_bytes(&MOVZX_BYTE_MEM_RSP_TO_EAX);
+    compiler.emit_bytes(&ADD_8_TO_RSP);
+    compiler.emit_bytes(&RET);
+
+    compiler.def_label(x86_64::STUB_GET, 1);
+    compiler.emit_bytes(&ADD_8_TO_RSP);
+    compiler.emit_bytes(&MOV_IMM32_TO_EAX);
+    compiler.emit_bytes(&(-1i32).to_le_bytes());
+    compiler.emit_bytes(&RET);
+}
+
+/// `grow_next(buf, cap)`: mmaps a fresh `cap + TAPE_CHUNK_SIZE` mapping
+/// (anonymous mappings come back zeroed, so the grown tail is already zero)
+/// and copies the live `cap` bytes in at the front.
+fn write_grow_next_stub(compiler: &mut Compiler) {
+    compiler.emit_bytes(&PUSH_RDI); // old buf
+    compiler.emit_bytes(&PUSH_RSI); // cap
+    compiler.emit_bytes(&MOV_RSI_TO_RAX);
+    compiler.emit_bytes(&ADD_IMM32_TO_RAX);
+    compiler.emit_bytes(&TAPE_CHUNK_SIZE.to_le_bytes());
+    compiler.emit_bytes(&MOV_RAX_TO_RSI); // length = cap + TAPE_CHUNK_SIZE
+    emit_anonymous_mmap(compiler);
+
+    compiler.emit_bytes(&POP_RCX); // cap
+    compiler.emit_bytes(&POP_RSI); // old buf (copy source)
+    compiler.emit_bytes(&MOV_RAX_TO_RDI); // new buf (copy dest, front)
+    compiler.emit_bytes(&PUSH_RAX); // save new buf to return
+    compiler.emit_bytes(&CLD);
+    compiler.emit_bytes(&REP_MOVSB);
+    compiler.emit_bytes(&POP_RAX);
+    compiler.emit_bytes(&RET);
+}
+
+/// `grow_prev(buf, cap)`: same as [`write_grow_next_stub`], but the live
+/// bytes land `TAPE_CHUNK_SIZE` in, leaving the prepended region (already
+/// zero from the mapping) in front.
+fn write_grow_prev_stub(compiler: &mut Compiler) {
+    compiler.emit_bytes(&PUSH_RDI);
+    compiler.emit_bytes(&PUSH_RSI);
+    compiler.emit_bytes(&MOV_RSI_TO_RAX);
+    compiler.emit_bytes(&ADD_IMM32_TO_RAX);
+    compiler.emit_bytes(&TAPE_CHUNK_SIZE.to_le_bytes());
+    compiler.emit_bytes(&MOV_RAX_TO_RSI);
+    emit_anonymous_mmap(compiler);
+
+    compiler.emit_bytes(&POP_RCX);
+    compiler.emit_bytes(&POP_RSI);
+    compiler.emit_bytes(&LEA_RAX_PLUS_IMM32_TO_RDI);
+    compiler.emit_bytes(&TAPE_CHUNK_SIZE.to_le_bytes());
+    compiler.emit_bytes(&PUSH_RAX);
+    compiler.emit_bytes(&CLD);
+    compiler.emit_bytes(&REP_MOVSB);
+    compiler.emit_bytes(&POP_RAX);
+    compiler.emit_bytes(&RET);
+}
+
+/// `mmap(NULL, rsi, PROT_READ|PROT_WRITE, MAP_PRIVATE|MAP_ANONYMOUS, -1, 0)`,
+/// leaving the new mapping's base pointer in `rax`. Caller sets up `rsi`
+/// (the length) beforehand.
+fn emit_anonymous_mmap(compiler: &mut Compiler) {
+    compiler.emit_bytes(&XOR_EDI_TO_EDI);
+    compiler.emit_bytes(&MOV_IMM32_TO_EDX);
+    compiler.emit_bytes(&PROT_READ_WRITE.to_le_bytes());
+    compiler.emit_bytes(&MOV_IMM32_TO_R10D);
+    compiler.emit_bytes(&MAP_PRIVATE_ANONYMOUS.to_le_bytes());
+    compiler.emit_bytes(&MOVABS_NEG1_TO_R8);
+    compiler.emit_bytes(&(-1i64).to_le_bytes());
+    compiler.emit_bytes(&XOR_R9D_TO_R9D);
+    compiler.emit_bytes(&MOV_IMM32_TO_EAX);
+    compiler.emit_bytes(&SYS_MMAP.to_le_bytes());
+    compiler.emit_bytes(&SYSCALL);
+}
+
+fn write_elf_header(file: &mut Vec<u8>) {
+    file.extend([0x7f, b'E', b'L', b'F']);
+    file.push(2); // ELFCLASS64
+    file.push(1); // ELFDATA2LSB
+    file.push(1); // EV_CURRENT
+    file.push(0); // ELFOSABI_SYSV
+    file.extend([0u8; 8]); // e_ident padding (abiversion + reserved)
+
+    file.extend(2u16.to_le_bytes()); // e_type = ET_EXEC
+    file.extend(0x3e_u16.to_le_bytes()); // e_machine = EM_X86_64
+    file.extend(1u32.to_le_bytes()); // e_version
+    file.extend((LOAD_BASE + HEADERS_SIZE).to_le_bytes()); // e_entry
+    file.extend(ELF_HEADER_SIZE.to_le_bytes()); // e_phoff
+    file.extend(0u64.to_le_bytes()); // e_shoff
+    file.extend(0u32.to_le_bytes()); // e_flags
+    file.extend((ELF_HEADER_SIZE as u16).to_le_bytes()); // e_ehsize
+    file.extend((PROGRAM_HEADER_SIZE as u16).to_le_bytes()); // e_phentsize
+    file.extend(1u16.to_le_bytes()); // e_phnum
+    file.extend(0u16.to_le_bytes()); // e_shentsize
+    file.extend(0u16.to_le_bytes()); // e_shnum
+    file.extend(0u16.to_le_bytes()); // e_shstrndx
+}
+
+fn write_program_header(file: &mut Vec<u8>, code_len: u64) {
+    let total_size = HEADERS_SIZE + code_len;
+    file.extend(1u32.to_le_bytes()); // p_type = PT_LOAD
+    file.extend(5u32.to_le_bytes()); // p_flags = PF_R | PF_X
+    file.extend(0u64.to_le_bytes()); // p_offset
+    file.extend(LOAD_BASE.to_le_bytes()); // p_vaddr
+    file.extend(LOAD_BASE.to_le_bytes()); // p_paddr
+    file.extend(total_size.to_le_bytes()); // p_filesz
+    file.extend(total_size.to_le_bytes()); // p_memsz
+    file.extend(0x1000u64.to_le_bytes()); // p_align
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::Instruction;
+
+    /// Writes `program` out as a standalone ELF binary under the system
+    /// temp directory and runs it, returning its stdout and exit code.
+    fn run_compiled(program: &Program) -> (Vec<u8>, i32) {
+        let path = std::env::temp_dir()
+            .join(format!("fast-bfc-elf-test-{}", std::process::id()));
+        compile_to_elf(program, &path).expect("compile_to_elf");
+        let output = std::process::Command::new(&path)
+            .output()
+            .expect("running compiled binary");
+        let _ = fs::remove_file(&path);
+        (output.stdout, output.status.code().expect("exit code"))
+    }
+
+    #[test]
+    fn add_and_put_round_trip() {
+        if !x86_64::TARGET_SUPPORTED {
+            return;
+        }
+        // 65 `+`s then `.`, already folded the way `ir::optimize::run` would
+        // leave it.
+        let program = Program {
+            code: vec![Instruction::Add(65), Instruction::Put, Instruction::Halt],
+        };
+        let (stdout, code) = run_compiled(&program);
+        assert_eq!(stdout, b"A");
+        assert_eq!(code, 0);
+    }
+
+    #[test]
+    fn growing_the_tape_past_a_chunk_boundary_does_not_crash() {
+        if !x86_64::TARGET_SUPPORTED {
+            return;
+        }
+        // Same boundary this backend shares with the in-process JIT: `>`
+        // repeated exactly `TAPE_CHUNK_SIZE` times used to leave the head on
+        // `idx == cap`, one past the last mapped page.
+        let program = Program {
+            code: vec![
+                Instruction::Move(TAPE_CHUNK_SIZE as isize),
+                Instruction::Put,
+                Instruction::Halt,
+            ],
+        };
+        let (stdout, code) = run_compiled(&program);
+        assert_eq!(stdout, vec![0]);
+        assert_eq!(code, 0);
+    }
+}
@@ -0,0 +1,109 @@
+//! Host-side functions called directly from JITed code.
+//!
+//! The tape is one contiguous, growable buffer, not a linked chain of
+//! chunks: the codegen'd prologue keeps the buffer's base pointer in a
+//! single register (`r12` on x86-64) and re-reads it from that register
+//! after every call that might reallocate, the same way `Vec::push` callers
+//! re-read a pointer after a push that might reallocate. [`grow_next`]
+//! extends the buffer by one chunk at the tail; [`grow_prev`] extends it at
+//! the head, shifting existing cells forward. Both return the buffer's new
+//! base pointer and are the only two operations that allocate.
+//!
+//! The live tape buffer is intentionally never freed: a compiled program's
+//! tape lives exactly as long as the process running it. Only the buffers
+//! that [`grow_next`]/[`grow_prev`] replace are released.
+
+use std::io::{self, Read, Write};
+
+/// Number of tape cells a single grow step adds. Chosen to match a page.
+pub const TAPE_CHUNK_SIZE: usize = 4096;
+
+/// Bundles the program's input/output streams behind a single pointer, so
+/// the JIT prologue only has to thread one extra register (`rbx`) through
+/// to [`put`]/[`get`] instead of two.
+pub struct IoContext<'a> {
+    pub input: &'a mut dyn Read,
+    pub output: &'a mut dyn Write,
+}
+
+/// Leaks `buf` and returns its base pointer, for handing tape memory off
+/// to JITed code that has no way to give it back.
+fn leak(buf: Vec<u8>) -> *mut u8 {
+    let mut buf = buf.into_boxed_slice();
+    let ptr = buf.as_mut_ptr();
+    std::mem::forget(buf);
+    ptr
+}
+
+/// Allocates a fresh, zero-initialized tape buffer of `len` cells. Used to
+/// seed the very first chunk before a program starts running.
+pub fn alloc_tape(len: usize) -> *mut u8 {
+    leak(vec![0u8; len])
+}
+
+/// Grows `buf` (of `cap` live bytes) by [`TAPE_CHUNK_SIZE`] at the tail and
+/// returns the new base pointer. Called from JITed code before the head
+/// would move past the last cell of the buffer (index >= capacity).
+///
+/// # Safety
+/// `buf` must be the base pointer of a tape buffer of exactly `cap` live
+/// bytes previously produced by this module (including by a prior call to
+/// [`grow_next`]/[`grow_prev`]).
+pub extern "C" fn grow_next(buf: *mut u8, cap: u64) -> *mut u8 {
+    let cap = cap as usize;
+    let mut grown = unsafe { Vec::from_raw_parts(buf, cap, cap) };
+    grown.resize(cap + TAPE_CHUNK_SIZE, 0);
+    leak(grown)
+}
+
+/// Grows `buf` by [`TAPE_CHUNK_SIZE`] at the head, shifting existing cells
+/// forward by that amount, and returns the new base pointer. Called from
+/// JITed code when the head walks off the start of the buffer (`r14 == 0`);
+/// the caller must also bump its head index by [`TAPE_CHUNK_SIZE`] so it
+/// keeps pointing at the same logical cell.
+///
+/// # Safety
+/// Same preconditions as [`grow_next`].
+pub extern "C" fn grow_prev(buf: *mut u8, cap: u64) -> *mut u8 {
+    let cap = cap as usize;
+    let old = unsafe { Vec::from_raw_parts(buf, cap, cap) };
+    let mut grown = vec![0u8; TAPE_CHUNK_SIZE];
+    grown.extend(old);
+    leak(grown)
+}
+
+/// Writes one cell value to the program's output stream.
+///
+/// Returns a negative value on I/O error, zero on success; the JIT tests the
+/// sign bit rather than unpacking a `Result` across the `extern "C"` border.
+///
+/// # Safety
+/// `ctx` must point to a live [`IoContext`].
+pub unsafe extern "C" fn put(ctx: *mut IoContext, value: u16) -> i8 {
+    let ctx = unsafe { &mut *ctx };
+    match ctx.output.write_all(&[value as u8]) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// Reads one byte from the program's input stream into the low byte of the
+/// return value.
+///
+/// Returns a negative value on EOF or I/O error; the JIT tests the sign bit
+/// the same way [`put`] does.
+///
+/// # Safety
+/// `ctx` must point to a live [`IoContext`].
+pub unsafe extern "C" fn get(ctx: *mut IoContext) -> i16 {
+    let ctx = unsafe { &mut *ctx };
+    let mut byte = [0u8];
+    match ctx.input.read(&mut byte) {
+        Ok(0) => -1,
+        Ok(_) => byte[0] as i16,
+        Err(err) if err.kind() == io::ErrorKind::Interrupted => {
+            unsafe { get(ctx as *mut IoContext) }
+        },
+        Err(_) => -1,
+    }
+}
@@ -0,0 +1,614 @@
+//! The hand-emitted x86-64 backend: walks an [`ir::Program`] and writes raw
+//! machine code bytes directly into a buffer, no assembler in between.
+//!
+//! This is the fast path on its native target (Linux/x86-64); see
+//! [`super::cranelift`] for the portable backend used everywhere else.
+
+use super::runtime::{self, IoContext};
+use super::ExitStatus;
+use crate::ir::{Instruction, Program};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Write};
+
+const PUSH_RBX: [u8; 1] = [0x53];
+const PUSH_R12: [u8; 2] = [0x41, 0x54];
+const PUSH_R13: [u8; 2] = [0x41, 0x55];
+const PUSH_R14: [u8; 2] = [0x41, 0x56];
+
+const POP_R14: [u8; 2] = [0x41, 0x5e];
+const POP_R13: [u8; 2] = [0x41, 0x5d];
+const POP_R12: [u8; 2] = [0x41, 0x5c];
+const POP_RBX: [u8; 1] = [0x5b];
+
+const MOV_RDI_TO_RBX: [u8; 3] = [0x48, 0x89, 0xfb];
+const MOV_RSI_TO_R12: [u8; 3] = [0x49, 0x89, 0xf4];
+const MOV_RDX_TO_R13: [u8; 3] = [0x49, 0x89, 0xd5];
+const MOV_R12_TO_RDI: [u8; 3] = [0x4c, 0x89, 0xe7];
+const MOV_R13_TO_RSI: [u8; 3] = [0x4c, 0x89, 0xee];
+const MOV_RAX_TO_R12: [u8; 3] = [0x49, 0x89, 0xc4];
+const MOV_RBX_TO_RDI: [u8; 3] = [0x48, 0x89, 0xdf];
+const MOV_AX_TO_SI: [u8; 3] = [0x66, 0x89, 0xc6];
+const MOV_AL_TO_MEM_R12_R14: [u8; 4] = [0x43, 0x88, 0x04, 0x34];
+const MOV_MEM_R12_R14_TO_AL: [u8; 4] = [0x43, 0x8a, 0x04, 0x34];
+const MOVABS_TO_RAX: [u8; 2] = [0x48, 0xb8];
+const MOV_IMM32_TO_EAX: [u8; 1] = [0xb8];
+
+const CMP_R14_WITH_R13: [u8; 3] = [0x4d, 0x39, 0xee];
+const TEST_R14_WITH_R14: [u8; 3] = [0x4d, 0x85, 0xf6];
+const TEST_AX_WITH_AX: [u8; 3] = [0x66, 0x85, 0xc0];
+const TEST_AL_WITH_AL: [u8; 2] = [0x84, 0xc0];
+
+const JMP_REL32: [u8; 1] = [0xe9];
+const JE_JZ_REL32: [u8; 2] = [0x0f, 0x84];
+const JNE_JNZ_REL32: [u8; 2] = [0x0f, 0x85];
+const JS_REL32: [u8; 2] = [0x0f, 0x88];
+const CALL_ABS_RAX: [u8; 2] = [0xff, 0xd0];
+
+const XOR_R14_TO_R14: [u8; 3] = [0x4d, 0x31, 0xf6];
+const XOR_EAX_TO_EAX: [u8; 2] = [0x31, 0xc0];
+
+const ADD_IMM32_TO_R13: [u8; 3] = [0x49, 0x81, 0xc5];
+const ADD_IMM32_TO_R14: [u8; 3] = [0x49, 0x81, 0xc6];
+
+const INC_R14: [u8; 3] = [0x49, 0xff, 0xc6];
+const DEC_R14: [u8; 3] = [0x49, 0xff, 0xce];
+
+const INCB_MEM_R12_R14: [u8; 4] = [0x43, 0xfe, 0x04, 0x34];
+const DECB_MEM_R12_R14: [u8; 4] = [0x43, 0xfe, 0x0c, 0x34];
+const ADD_IMM8_TO_MEM_R12_R14: [u8; 4] = [0x43, 0x80, 0x04, 0x34];
+const MOVB_IMM8_TO_MEM_R12_R14: [u8; 4] = [0x43, 0xc6, 0x04, 0x34];
+
+const MOV_R13_TO_RAX: [u8; 3] = [0x4c, 0x89, 0xe8];
+const SUB_R14_FROM_RAX: [u8; 3] = [0x4c, 0x29, 0xf0];
+const CMP_IMM32_WITH_RAX: [u8; 3] = [0x48, 0x81, 0xf8];
+const CMP_IMM32_WITH_R14: [u8; 3] = [0x49, 0x81, 0xfe];
+const JGE_REL32: [u8; 2] = [0x0f, 0x8d];
+const JG_REL32: [u8; 2] = [0x0f, 0x8f];
+
+const SUB_IMM8_FROM_RSP: [u8; 4] = [0x48, 0x83, 0xec, 0x08];
+const ADD_IMM8_TO_RSP: [u8; 4] = [0x48, 0x83, 0xc4, 0x08];
+
+const RET: [u8; 1] = [0xc3];
+const CALL_REL32: [u8; 1] = [0xe8];
+const MOV_EAX_TO_EDI: [u8; 2] = [0x89, 0xc7];
+const SYSCALL: [u8; 2] = [0x0f, 0x05];
+const EXIT_GROUP: u32 = 231;
+
+pub const TARGET_SUPPORTED: bool =
+    cfg!(all(target_os = "linux", target_arch = "x86_64"));
+
+/// Entry point signature `write_enter`'s prologue expects: IO context, tape
+/// base pointer, tape capacity (same ABI as [`super::cranelift::Executable`]'s
+/// `MainFn`, so both backends are interchangeable from the caller's side).
+type EntryFn = unsafe extern "C" fn(*mut IoContext, *mut u8, i64) -> i32;
+
+/// A JIT-compiled program mapped into executable memory. The mapping is
+/// never writable and executable at the same time: [`Executable::new`]
+/// writes the code while the page is `PROT_READ | PROT_WRITE`, then flips
+/// it to `PROT_READ | PROT_EXEC` before anything can call into it.
+pub struct Executable {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl Executable {
+    fn new(buf: Vec<u8>) -> Result<Self, super::Error> {
+        let len = buf.len();
+        let map = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if map == libc::MAP_FAILED {
+            Err(super::Error::AllocError)?;
+        }
+        let ptr = map as *mut u8;
+        unsafe {
+            std::ptr::copy_nonoverlapping(buf.as_ptr(), ptr, len);
+        }
+        let protected = unsafe {
+            libc::mprotect(map, len, libc::PROT_READ | libc::PROT_EXEC)
+        };
+        if protected != 0 {
+            unsafe {
+                libc::munmap(map, len);
+            }
+            Err(super::Error::AllocError)?;
+        }
+        Ok(Self { ptr, len })
+    }
+
+    /// Runs the compiled program against a fresh tape chunk, using the
+    /// register ABI `write_enter` already expects: `rdi`/`rsi`/`rdx` carry
+    /// the IO context, tape base pointer and tape capacity into
+    /// `rbx`/`r12`/`r13`.
+    pub fn run(
+        &self,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+    ) -> ExitStatus {
+        let mut io = IoContext { input, output };
+        let tape = runtime::alloc_tape(runtime::TAPE_CHUNK_SIZE);
+        let entry: EntryFn = unsafe { std::mem::transmute(self.ptr) };
+        let code = unsafe {
+            entry(&mut io as *mut IoContext, tape, runtime::TAPE_CHUNK_SIZE as i64)
+        };
+        match code {
+            0 => ExitStatus::Halted,
+            1 => ExitStatus::InputEof,
+            2 => ExitStatus::WriteError,
+            _ => unreachable!("jit entry returned unknown exit code {code}"),
+        }
+    }
+}
+
+impl Drop for Executable {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr as *mut libc::c_void, self.len);
+        }
+    }
+}
+
+pub fn compile(program: &Program) -> Result<Executable, super::Error> {
+    if !TARGET_SUPPORTED {
+        Err(super::Error::UnsupportedTarget)?;
+    }
+
+    let mut compiler = Compiler::new(RuntimeLinkage::Host);
+    let last_ir_label = program.code.len();
+
+    compiler.write_enter();
+    compiler.first_pass(program);
+    compiler.write_leave(last_ir_label);
+    compiler.second_pass()?;
+
+    Executable::new(compiler.buf)
+}
+
+/// How `Compiler` reaches `runtime::put`/`get`/`grow_next`/`grow_prev`.
+/// [`x86_64::compile`](compile) (JIT) calls the host functions directly;
+/// [`super::elf::compile_to_elf`] has no host process to call into, so it
+/// links in its own syscall stubs instead and calls those by the same
+/// label-placeholder mechanism as any other jump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeLinkage {
+    Host,
+    Stub,
+}
+
+/// Reserved `ir_label`s (outside the range any real program ever reaches)
+/// for the four statically-linked stubs `RuntimeLinkage::Stub` calls into.
+pub const STUB_PUT: usize = usize::MAX;
+pub const STUB_GET: usize = usize::MAX - 1;
+pub const STUB_GROW_NEXT: usize = usize::MAX - 2;
+pub const STUB_GROW_PREV: usize = usize::MAX - 3;
+
+fn write_absolute_call(
+    buf: &mut Vec<u8>,
+    func_ptr: *const u8,
+) -> Result<(), super::Error> {
+    buf.extend(MOVABS_TO_RAX);
+    buf.extend((func_ptr as usize as u64).to_le_bytes());
+    buf.extend(CALL_ABS_RAX);
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct Compiler {
+    buf: Vec<u8>,
+    placeholders: BTreeMap<usize, (usize, usize)>,
+    labels: HashMap<(usize, usize), usize>,
+    runtime: RuntimeLinkage,
+}
+
+impl Compiler {
+    pub fn new(runtime: RuntimeLinkage) -> Self {
+        Self {
+            buf: Vec::new(),
+            placeholders: BTreeMap::new(),
+            labels: HashMap::new(),
+            runtime,
+        }
+    }
+
+    /// Appends raw bytes with no label bookkeeping, for the AOT backend's
+    /// straight-line syscall stubs.
+    pub fn emit_bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    /// `CALL rel32` to a label, resolved the same way any other jump is.
+    pub fn call_relative(&mut self, ir_label: usize, sub_label: usize) {
+        self.buf.extend(CALL_REL32);
+        self.make_placeholder(ir_label, sub_label);
+    }
+
+    fn call_runtime_put(&mut self) {
+        match self.runtime {
+            RuntimeLinkage::Host => self.call_absolute(runtime::put as *const u8),
+            RuntimeLinkage::Stub => self.call_relative(STUB_PUT, 0),
+        }
+    }
+
+    fn call_runtime_get(&mut self) {
+        match self.runtime {
+            RuntimeLinkage::Host => self.call_absolute(runtime::get as *const u8),
+            RuntimeLinkage::Stub => self.call_relative(STUB_GET, 0),
+        }
+    }
+
+    fn call_runtime_grow_next(&mut self) {
+        match self.runtime {
+            RuntimeLinkage::Host => {
+                self.call_absolute(runtime::grow_next as *const u8)
+            },
+            RuntimeLinkage::Stub => self.call_relative(STUB_GROW_NEXT, 0),
+        }
+    }
+
+    fn call_runtime_grow_prev(&mut self) {
+        match self.runtime {
+            RuntimeLinkage::Host => {
+                self.call_absolute(runtime::grow_prev as *const u8)
+            },
+            RuntimeLinkage::Stub => self.call_relative(STUB_GROW_PREV, 0),
+        }
+    }
+
+    pub fn first_pass(&mut self, program: &Program) {
+        let last_ir_label = program.code.len();
+        for (ir_label, instr) in program.code.iter().enumerate() {
+            self.def_main_label(ir_label);
+            self.handle_instruction(ir_label, *instr, last_ir_label);
+        }
+        self.def_main_label(last_ir_label);
+    }
+
+    pub fn second_pass(&mut self) -> Result<(), super::Error> {
+        for (placeholder_label, (ir_label, sub_ir_label)) in &self.placeholders
+        {
+            let Some(label) = self.labels.get(&(*ir_label, *sub_ir_label))
+            else {
+                Err(super::Error::BadLabelIndex(*ir_label))?
+            };
+            // rel32 operands are relative to the address of the *next*
+            // instruction (right after this 4-byte operand), not an
+            // absolute buffer offset.
+            let next_instr = *placeholder_label as i64 + 4;
+            let displacement = *label as i64 - next_instr;
+            let label_buf = (displacement as i32).to_le_bytes();
+            self.buf[*placeholder_label .. *placeholder_label + 4]
+                .copy_from_slice(&label_buf[..]);
+        }
+        Ok(())
+    }
+
+    pub fn handle_instruction(
+        &mut self,
+        ir_label: usize,
+        instr: Instruction,
+        last_ir_label: usize,
+    ) {
+        match instr {
+            Instruction::Add(delta) => self.write_add(delta),
+            Instruction::Move(delta) => self.write_move(ir_label, delta),
+            Instruction::SetZero => self.write_set_zero(),
+            Instruction::Inc => self.write_inc(),
+            Instruction::Dec => self.write_dec(),
+            Instruction::Next => self.write_next(ir_label),
+            Instruction::Prev => self.write_prev(ir_label),
+            Instruction::Get => self.write_get(ir_label, last_ir_label),
+            Instruction::Put => self.write_put(last_ir_label),
+            Instruction::Jz(target_ir_label) => self.write_jz(target_ir_label),
+            Instruction::Jnz(target_ir_label) => {
+                self.write_jnz(target_ir_label)
+            },
+            Instruction::Halt => self.write_halt(last_ir_label),
+        }
+    }
+
+    pub fn def_main_label(&mut self, ir_label: usize) {
+        self.def_label(ir_label, 0)
+    }
+
+    pub fn def_label(&mut self, ir_label: usize, sub_label: usize) {
+        self.labels.insert((ir_label, sub_label), self.buf.len());
+    }
+
+    pub fn make_placeholder(&mut self, ir_label: usize, sub_label: usize) {
+        self.placeholders.insert(self.buf.len(), (ir_label, sub_label));
+        self.buf.extend(0u32.to_le_bytes());
+    }
+
+    pub fn call_absolute(&mut self, func_ptr: *const u8) {
+        self.buf.extend(MOVABS_TO_RAX);
+        self.buf.extend((func_ptr as usize as u64).to_le_bytes());
+        self.buf.extend(CALL_ABS_RAX);
+    }
+
+    pub fn write_enter(&mut self) {
+        self.buf.extend(PUSH_RBX);
+        self.buf.extend(PUSH_R12);
+        self.buf.extend(PUSH_R13);
+        self.buf.extend(PUSH_R14);
+        // The four pushes above are an even count, which leaves `rsp` at
+        // the same 16-byte-alignment parity it had on entry (`%16 == 8`,
+        // the standard post-`call` offset) instead of realigning it; every
+        // `call` this function makes afterwards needs `rsp % 16 == 0`, so
+        // this padding slot restores that. `write_leave`/`write_leave_exit`
+        // undo it before popping the saved registers back off.
+        self.buf.extend(SUB_IMM8_FROM_RSP);
+        self.buf.extend(MOV_RDI_TO_RBX);
+        self.buf.extend(MOV_RSI_TO_R12);
+        self.buf.extend(MOV_RDX_TO_R13);
+        self.buf.extend(XOR_R14_TO_R14);
+    }
+
+    /// Emits the three landing points `write_put`/`write_get` jump to on
+    /// their way out, each loading the matching [`ExitStatus`](super::ExitStatus)
+    /// discriminant into `eax` before converging on a shared epilogue.
+    /// `(ir_label, 0)` (normal completion) is already defined by
+    /// `first_pass`, right where this continues writing.
+    pub fn write_leave(&mut self, ir_label: usize) {
+        self.buf.extend(XOR_EAX_TO_EAX); // Halted = 0
+        self.buf.extend(JMP_REL32);
+        self.make_placeholder(ir_label, 3);
+
+        self.def_label(ir_label, 1);
+        self.buf.extend(MOV_IMM32_TO_EAX); // InputEof = 1
+        self.buf.extend(1u32.to_le_bytes());
+        self.buf.extend(JMP_REL32);
+        self.make_placeholder(ir_label, 3);
+
+        self.def_label(ir_label, 2);
+        self.buf.extend(MOV_IMM32_TO_EAX); // WriteError = 2
+        self.buf.extend(2u32.to_le_bytes());
+
+        self.def_label(ir_label, 3);
+        self.buf.extend(ADD_IMM8_TO_RSP); // undo write_enter's alignment pad
+        self.buf.extend(POP_R14);
+        self.buf.extend(POP_R13);
+        self.buf.extend(POP_R12);
+        self.buf.extend(POP_RBX);
+        self.buf.extend(RET);
+    }
+
+    /// AOT counterpart to [`write_leave`](Self::write_leave): same three
+    /// landing points and exit status discriminants, but ends in an
+    /// `exit_group` syscall rather than popping back into a caller — a
+    /// freestanding ELF binary's entry point has no caller to return to.
+    pub fn write_leave_exit(&mut self, ir_label: usize) {
+        self.buf.extend(XOR_EAX_TO_EAX); // Halted = 0
+        self.buf.extend(JMP_REL32);
+        self.make_placeholder(ir_label, 3);
+
+        self.def_label(ir_label, 1);
+        self.buf.extend(MOV_IMM32_TO_EAX); // InputEof = 1
+        self.buf.extend(1u32.to_le_bytes());
+        self.buf.extend(JMP_REL32);
+        self.make_placeholder(ir_label, 3);
+
+        self.def_label(ir_label, 2);
+        self.buf.extend(MOV_IMM32_TO_EAX); // WriteError = 2
+        self.buf.extend(2u32.to_le_bytes());
+
+        self.def_label(ir_label, 3);
+        self.buf.extend(MOV_EAX_TO_EDI);
+        self.buf.extend(MOV_IMM32_TO_EAX);
+        self.buf.extend(EXIT_GROUP.to_le_bytes());
+        self.buf.extend(SYSCALL);
+    }
+
+    pub fn write_inc(&mut self) {
+        self.buf.extend(INCB_MEM_R12_R14);
+    }
+
+    pub fn write_dec(&mut self) {
+        self.buf.extend(DECB_MEM_R12_R14);
+    }
+
+    /// `Add(delta)`: folded run of `+`/`-`.
+    pub fn write_add(&mut self, delta: i8) {
+        self.buf.extend(ADD_IMM8_TO_MEM_R12_R14);
+        self.buf.extend(delta.to_le_bytes());
+    }
+
+    /// `SetZero`: the `[-]`/`[+]` idiom.
+    pub fn write_set_zero(&mut self) {
+        self.buf.extend(MOVB_IMM8_TO_MEM_R12_R14);
+        self.buf.extend(0u8.to_le_bytes());
+    }
+
+    /// `Move(delta)`: folded run of `>`/`<`. Unlike a single `write_next`/
+    /// `write_prev`, `delta` may cross more than one `TAPE_CHUNK_SIZE`
+    /// boundary, so the growth check is a loop rather than a single
+    /// branch.
+    pub fn write_move(&mut self, ir_label: usize, delta: isize) {
+        if delta == 0 {
+            return;
+        }
+        if delta > 0 {
+            self.def_label(ir_label, 2);
+            self.buf.extend(MOV_R13_TO_RAX);
+            self.buf.extend(SUB_R14_FROM_RAX);
+            self.buf.extend(CMP_IMM32_WITH_RAX);
+            self.buf.extend((delta as i32).to_le_bytes());
+            // Strictly greater, not >=: the head must land `< cap` after
+            // the move, so a chunk that leaves exactly `delta` cells
+            // remaining still needs one more grow.
+            self.buf.extend(JG_REL32);
+            self.make_placeholder(ir_label, 1);
+            self.buf.extend(MOV_R12_TO_RDI);
+            self.buf.extend(MOV_R13_TO_RSI);
+            self.call_runtime_grow_next();
+            self.buf.extend(MOV_RAX_TO_R12);
+            self.buf.extend(ADD_IMM32_TO_R13);
+            self.buf.extend((runtime::TAPE_CHUNK_SIZE as u32).to_le_bytes());
+            self.buf.extend(JMP_REL32);
+            self.make_placeholder(ir_label, 2);
+            self.def_label(ir_label, 1);
+            self.buf.extend(ADD_IMM32_TO_R14);
+            self.buf.extend((delta as i32).to_le_bytes());
+        } else {
+            let count = (-delta) as i32;
+            self.def_label(ir_label, 2);
+            self.buf.extend(CMP_IMM32_WITH_R14);
+            self.buf.extend(count.to_le_bytes());
+            self.buf.extend(JGE_REL32);
+            self.make_placeholder(ir_label, 1);
+            self.buf.extend(MOV_R12_TO_RDI);
+            self.buf.extend(MOV_R13_TO_RSI);
+            self.call_runtime_grow_prev();
+            self.buf.extend(MOV_RAX_TO_R12);
+            self.buf.extend(ADD_IMM32_TO_R14);
+            self.buf.extend((runtime::TAPE_CHUNK_SIZE as u32).to_le_bytes());
+            self.buf.extend(ADD_IMM32_TO_R13);
+            self.buf.extend((runtime::TAPE_CHUNK_SIZE as u32).to_le_bytes());
+            self.buf.extend(JMP_REL32);
+            self.make_placeholder(ir_label, 2);
+            self.def_label(ir_label, 1);
+            self.buf.extend(ADD_IMM32_TO_R14);
+            self.buf.extend((-count).to_le_bytes());
+        }
+    }
+
+    pub fn write_next(&mut self, ir_label: usize) {
+        // Same strict check as `write_move`'s forward path: growing only
+        // when `idx == cap` lets the post-increment head land exactly on
+        // `cap` (one past the last mapped cell) whenever it started one
+        // short, so grow whenever fewer than 2 cells remain instead.
+        self.buf.extend(MOV_R13_TO_RAX);
+        self.buf.extend(SUB_R14_FROM_RAX);
+        self.buf.extend(CMP_IMM32_WITH_RAX);
+        self.buf.extend(1i32.to_le_bytes());
+        self.buf.extend(JG_REL32);
+        self.make_placeholder(ir_label, 1);
+        self.buf.extend(MOV_R12_TO_RDI);
+        self.buf.extend(MOV_R13_TO_RSI);
+        self.call_runtime_grow_next();
+        self.buf.extend(MOV_RAX_TO_R12);
+        self.buf.extend(ADD_IMM32_TO_R13);
+        self.buf.extend((runtime::TAPE_CHUNK_SIZE as u32).to_le_bytes());
+        self.def_label(ir_label, 1);
+        self.buf.extend(INC_R14);
+    }
+
+    pub fn write_prev(&mut self, ir_label: usize) {
+        self.buf.extend(TEST_R14_WITH_R14);
+        self.buf.extend(JNE_JNZ_REL32);
+        self.make_placeholder(ir_label, 1);
+        self.buf.extend(MOV_R12_TO_RDI);
+        self.buf.extend(MOV_R13_TO_RSI);
+        self.call_runtime_grow_prev();
+        self.buf.extend(ADD_IMM32_TO_R14);
+        self.buf.extend((runtime::TAPE_CHUNK_SIZE as u32).to_le_bytes());
+        self.buf.extend(MOV_RAX_TO_R12);
+        self.buf.extend(ADD_IMM32_TO_R13);
+        self.buf.extend((runtime::TAPE_CHUNK_SIZE as u32).to_le_bytes());
+        self.def_label(ir_label, 1);
+        self.buf.extend(DEC_R14);
+    }
+
+    pub fn write_put(&mut self, last_ir_label: usize) {
+        self.buf.extend(MOV_RBX_TO_RDI);
+        self.buf.extend(XOR_EAX_TO_EAX);
+        self.buf.extend(MOV_MEM_R12_R14_TO_AL);
+        self.buf.extend(MOV_AX_TO_SI);
+        self.call_runtime_put();
+        self.buf.extend(TEST_AL_WITH_AL);
+        self.buf.extend(JS_REL32);
+        self.make_placeholder(last_ir_label, 2);
+    }
+
+    pub fn write_get(&mut self, ir_label: usize, last_ir_label: usize) {
+        self.buf.extend(CMP_R14_WITH_R13);
+        self.buf.extend(JNE_JNZ_REL32);
+        self.make_placeholder(ir_label, 1);
+        self.buf.extend(MOV_R12_TO_RDI);
+        self.buf.extend(MOV_R13_TO_RSI);
+        self.call_runtime_grow_next();
+        self.buf.extend(MOV_RAX_TO_R12);
+        self.buf.extend(ADD_IMM32_TO_R13);
+        self.buf.extend((runtime::TAPE_CHUNK_SIZE as u32).to_le_bytes());
+        self.def_label(ir_label, 1);
+        self.buf.extend(MOV_RBX_TO_RDI);
+        self.call_runtime_get();
+        self.buf.extend(TEST_AX_WITH_AX);
+        self.buf.extend(JS_REL32);
+        self.make_placeholder(last_ir_label, 1);
+        // `runtime::get` returns the byte zero-extended into `ax`, so a
+        // single-byte store of `al` lands it in `tape[head]`; the previous
+        // `ror ax,8` + word store instead swapped it into `tape[head+1]`
+        // and zeroed `tape[head]`.
+        self.buf.extend(MOV_AL_TO_MEM_R12_R14);
+    }
+
+    pub fn write_halt(&mut self, last_ir_label: usize) {
+        self.buf.extend(JMP_REL32);
+        self.make_placeholder(last_ir_label, 0);
+    }
+
+    pub fn write_jz(&mut self, target_ir_label: usize) {
+        self.buf.extend(JE_JZ_REL32);
+        self.make_placeholder(target_ir_label, 0);
+    }
+
+    pub fn write_jnz(&mut self, target_ir_label: usize) {
+        self.buf.extend(JNE_JNZ_REL32);
+        self.make_placeholder(target_ir_label, 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::Instruction;
+
+    #[test]
+    fn runs_add_and_put_round_trip() {
+        if !TARGET_SUPPORTED {
+            return;
+        }
+        // 65 `+`s then `.`, already folded the way `ir::optimize::run` would
+        // leave it.
+        let program = Program {
+            code: vec![Instruction::Add(65), Instruction::Put, Instruction::Halt],
+        };
+        let exe = compile(&program).expect("compile");
+        let mut input: &[u8] = &[];
+        let mut output = Vec::new();
+        let status = exe.run(&mut input, &mut output);
+        assert_eq!(status, ExitStatus::Halted);
+        assert_eq!(output, b"A");
+    }
+
+    #[test]
+    fn growing_the_tape_past_a_chunk_boundary_does_not_crash() {
+        if !TARGET_SUPPORTED {
+            return;
+        }
+        // `>` repeated exactly `TAPE_CHUNK_SIZE` times used to land the head
+        // on `idx == cap` (one past the last mapped cell); the following
+        // `.` would then read out of bounds.
+        let mut code = vec![Instruction::Move(runtime::TAPE_CHUNK_SIZE as isize)];
+        code.push(Instruction::Put);
+        code.push(Instruction::Halt);
+        let program = Program { code };
+        let exe = compile(&program).expect("compile");
+        let mut input: &[u8] = &[];
+        let mut output = Vec::new();
+        let status = exe.run(&mut input, &mut output);
+        assert_eq!(status, ExitStatus::Halted);
+        assert_eq!(output, vec![0]);
+    }
+}
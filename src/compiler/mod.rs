@@ -0,0 +1,21 @@
+pub mod interp;
+pub mod jit;
+
+/// How a compiled or interpreted program stopped running. Both [`jit`]'s
+/// backends and [`interp`] report the same outcome through this type,
+/// rather than each backend signaling errors its own way (the JIT used to
+/// just test a register's sign bit and jump to a shared "halt" label).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum ExitStatus {
+    /// The program ran off the end of its code normally, or hit an
+    /// explicit `Instruction::Halt`.
+    Halted = 0,
+    /// A `,` read past the end of input, or the input stream errored.
+    InputEof = 1,
+    /// A `.` failed to write to the output stream.
+    WriteError = 2,
+    /// [`interp`]'s instruction budget ran out before the program halted.
+    /// The JIT backends never produce this; they have no budget.
+    BudgetExhausted = 3,
+}
@@ -0,0 +1,44 @@
+//! Intermediate representation shared by every codegen backend.
+//!
+//! A [`Program`] is produced once, ahead of time, by resolving Brainfuck's
+//! `[`/`]` matching into absolute IR labels (plain indices into
+//! [`Program::code`]). Backends never see source text or braces, only this
+//! flat, already-resolved instruction list. [`optimize::run`] rewrites a
+//! freshly parsed `Program` into an equivalent, denser one before any
+//! backend sees it.
+
+pub mod optimize;
+
+/// One IR-level operation.
+///
+/// `Jz`/`Jnz` targets are IR labels (indices into [`Program::code`]), not
+/// byte offsets; each backend is responsible for turning them into whatever
+/// its own control-flow primitive is (a relative jump, a `brif`, a loop in
+/// an interpreter dispatch).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// Add `.0` to the current cell, wrapping. Folded from a maximal run of
+    /// `Inc`/`Dec`.
+    Add(i8),
+    /// Move the head by `.0` cells (negative moves left). Folded from a
+    /// maximal run of `Next`/`Prev`.
+    Move(isize),
+    /// Set the current cell to `0`. Recognized from the `[-]`/`[+]` idiom:
+    /// a `Jz`/`Inc`-or-`Dec`/`Jnz` loop that only clears its own cell.
+    SetZero,
+    Inc,
+    Dec,
+    Next,
+    Prev,
+    Get,
+    Put,
+    Jz(usize),
+    Jnz(usize),
+    Halt,
+}
+
+/// A fully parsed program, ready for optimization and/or codegen.
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    pub code: Vec<Instruction>,
+}
@@ -0,0 +1,231 @@
+//! Coalescing and clear-loop folding, run between parsing and codegen.
+//!
+//! Unoptimized IR has one [`Instruction::Inc`]/[`Instruction::Dec`] per `+`
+//! /`-` and one [`Instruction::Next`]/[`Instruction::Prev`] per `>`/`<`, so
+//! `++++++++` or `>>>>>` show up as runs of identical instructions. This
+//! pass rewrites such runs into single [`Instruction::Add`]/
+//! [`Instruction::Move`] instructions, and recognizes the `[-]`/`[+]` idiom
+//! (a loop that only decrements or increments its own cell) as
+//! [`Instruction::SetZero`].
+//!
+//! Folding changes how many instructions the program has, which means IR
+//! labels shift; [`run`] tracks an old-label -> new-label mapping as it
+//! goes and uses it to rewrite every `Jz`/`Jnz` target at the end, so loop
+//! targets stay correct even when they used to point into the middle of a
+//! run that's now a single fused instruction.
+
+use super::{Instruction, Program};
+
+/// Runs the pass, returning an equivalent, denser program.
+pub fn run(program: &Program) -> Program {
+    let code = &program.code;
+    let old_len = code.len();
+    // Maps every old IR label (plus one past the end, for `Halt`'s and
+    // `Jz`/`Jnz`'s "off the end" target) to where it landed in `new_code`.
+    let mut old_to_new = vec![0usize; old_len + 1];
+    let mut new_code = Vec::with_capacity(old_len);
+
+    let mut i = 0;
+    while i < old_len {
+        old_to_new[i] = new_code.len();
+
+        if let Some(len) = clear_loop_len(code, i) {
+            for offset in 1 .. len {
+                old_to_new[i + offset] = new_code.len();
+            }
+            new_code.push(Instruction::SetZero);
+            i += len;
+            continue;
+        }
+
+        match code[i] {
+            Instruction::Inc | Instruction::Dec => {
+                let mut delta = 0i32;
+                let mut len = 0;
+                while i + len < old_len
+                    && matches!(
+                        code[i + len],
+                        Instruction::Inc | Instruction::Dec
+                    )
+                {
+                    delta += match code[i + len] {
+                        Instruction::Inc => 1,
+                        Instruction::Dec => -1,
+                        _ => unreachable!(),
+                    };
+                    old_to_new[i + len] = new_code.len();
+                    len += 1;
+                }
+                new_code.push(Instruction::Add(delta as i8));
+                i += len;
+            },
+            Instruction::Next | Instruction::Prev => {
+                let mut delta = 0isize;
+                let mut len = 0;
+                while i + len < old_len
+                    && matches!(
+                        code[i + len],
+                        Instruction::Next | Instruction::Prev
+                    )
+                {
+                    delta += match code[i + len] {
+                        Instruction::Next => 1,
+                        Instruction::Prev => -1,
+                        _ => unreachable!(),
+                    };
+                    old_to_new[i + len] = new_code.len();
+                    len += 1;
+                }
+                new_code.push(Instruction::Move(delta));
+                i += len;
+            },
+            other => {
+                new_code.push(other);
+                i += 1;
+            },
+        }
+    }
+    old_to_new[old_len] = new_code.len();
+
+    for instr in &mut new_code {
+        match instr {
+            Instruction::Jz(target) => *target = old_to_new[*target],
+            Instruction::Jnz(target) => *target = old_to_new[*target],
+            _ => {},
+        }
+    }
+
+    Program { code: new_code }
+}
+
+/// If `code[i..]` starts with the `[-]`/`[+]` idiom (a `Jz` whose target is
+/// the instruction right after the loop's `Jnz`, a single `Inc` or `Dec`,
+/// then a `Jnz` back to the `Jz`), returns the idiom's length in
+/// instructions (always 3). `None` otherwise, including for loops that
+/// merely look similar (e.g. ones jumping elsewhere).
+fn clear_loop_len(code: &[Instruction], i: usize) -> Option<usize> {
+    let [Instruction::Jz(z_target), delta_instr, Instruction::Jnz(nz_target)] =
+        code.get(i .. i + 3)?
+    else {
+        return None;
+    };
+    let is_delta = matches!(delta_instr, Instruction::Inc | Instruction::Dec);
+    (is_delta && *z_target == i + 3 && *nz_target == i).then_some(3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folds_inc_dec_run_into_add() {
+        let program = Program {
+            code: vec![
+                Instruction::Inc,
+                Instruction::Inc,
+                Instruction::Dec,
+                Instruction::Inc,
+                Instruction::Halt,
+            ],
+        };
+        let optimized = run(&program);
+        assert_eq!(
+            optimized.code,
+            vec![Instruction::Add(2), Instruction::Halt]
+        );
+    }
+
+    #[test]
+    fn folds_next_prev_run_into_move() {
+        let program = Program {
+            code: vec![
+                Instruction::Next,
+                Instruction::Next,
+                Instruction::Prev,
+                Instruction::Halt,
+            ],
+        };
+        let optimized = run(&program);
+        assert_eq!(
+            optimized.code,
+            vec![Instruction::Move(1), Instruction::Halt]
+        );
+    }
+
+    #[test]
+    fn folds_clear_loop_into_set_zero() {
+        // `[-]`
+        let program = Program {
+            code: vec![
+                Instruction::Jz(3),
+                Instruction::Dec,
+                Instruction::Jnz(0),
+                Instruction::Halt,
+            ],
+        };
+        let optimized = run(&program);
+        assert_eq!(
+            optimized.code,
+            vec![Instruction::SetZero, Instruction::Halt]
+        );
+    }
+
+    #[test]
+    fn does_not_fold_loop_jumping_elsewhere() {
+        // Same shape as `[-]` but `Jz` targets past the loop's end, so it
+        // isn't the clear-cell idiom and must be left as a real loop (its
+        // lone `Dec`/`Inc` still fold into single-instruction `Add`s, same
+        // as any other run).
+        let program = Program {
+            code: vec![
+                Instruction::Jz(4),
+                Instruction::Dec,
+                Instruction::Jnz(0),
+                Instruction::Inc,
+                Instruction::Halt,
+            ],
+        };
+        let optimized = run(&program);
+        assert_eq!(
+            optimized.code,
+            vec![
+                Instruction::Jz(4),
+                Instruction::Add(-1),
+                Instruction::Jnz(0),
+                Instruction::Add(1),
+                Instruction::Halt,
+            ]
+        );
+    }
+
+    #[test]
+    fn remaps_jump_targets_after_fusing_runs() {
+        // `++++[-]>>` followed by a jump back to the top of the `+` run.
+        let program = Program {
+            code: vec![
+                Instruction::Inc,
+                Instruction::Inc,
+                Instruction::Inc,
+                Instruction::Inc,
+                Instruction::Jz(7),
+                Instruction::Dec,
+                Instruction::Jnz(4),
+                Instruction::Next,
+                Instruction::Next,
+                Instruction::Jz(0),
+                Instruction::Halt,
+            ],
+        };
+        let optimized = run(&program);
+        assert_eq!(
+            optimized.code,
+            vec![
+                Instruction::Add(4),
+                Instruction::SetZero,
+                Instruction::Move(2),
+                Instruction::Jz(0),
+                Instruction::Halt,
+            ]
+        );
+    }
+}